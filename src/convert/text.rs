@@ -2,6 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::collections::HashMap;
+
+use rustybuzz;
+use ttf_parser;
+use unicode_script::UnicodeScript;
+
 use svgdom;
 use svgdom::types::{
     FuzzyEq,
@@ -21,23 +27,32 @@ use traits::{
 use super::{
     fill,
     stroke,
+    path,
 };
 
 
 pub fn convert(
     defs: &[dom::RefElement],
+    fonts: &FontDatabase,
     text_elem: &svgdom::Node,
 ) -> Option<dom::Element>
 {
     let attrs = text_elem.attributes();
     let ts = attrs.get_transform(AId::Transform).unwrap_or_default();
+    let root_style = TextStyle::root(defs, &attrs);
+
+    let kind = if let Some(text_path) = text_elem.children().find(|n| n.is_tag_name(EId::TextPath)) {
+        convert_text_path(defs, fonts, text_elem, &text_path, &root_style).map(dom::ElementKind::TextPath)
+    } else {
+        convert_chunks(defs, fonts, text_elem, &root_style).map(|children| dom::ElementKind::Text(dom::Text {
+            children,
+        }))
+    };
 
-    if let Some(chunks) = convert_chunks(defs, text_elem) {
+    if let Some(kind) = kind {
         Some(dom::Element {
             id: String::new(),
-            kind: dom::ElementKind::Text(dom::Text {
-                children: chunks,
-            }),
+            kind,
             transform: ts,
         })
     } else {
@@ -45,9 +60,186 @@ pub fn convert(
     }
 }
 
+/// Converts a `<textPath>` child of a `<text>` element: resolves the referenced
+/// `<path>`, builds an arc-length parameterization of it, and lays the path's own
+/// `tspan` children out along that path.
+fn convert_text_path(
+    defs: &[dom::RefElement],
+    fonts: &FontDatabase,
+    text_elem: &svgdom::Node,
+    text_path: &svgdom::Node,
+    root_style: &TextStyle,
+) -> Option<dom::TextPath> {
+    let attrs = text_path.attributes();
+
+    let href = attrs.get_string(AId::Href)?;
+    let id = href.trim_start_matches('#');
+    let path_node = text_elem.root().descendants()
+        .find(|n| n.is_tag_name(EId::Path) && n.id().as_str() == id)?;
+
+    let segments = path::convert_data(&path_node);
+    if segments.is_empty() {
+        return None;
+    }
+
+    let arc_length = ArcLengthTable::build(&segments);
+    let start_offset = resolve_start_offset(&attrs, arc_length.total_length());
+
+    let style = root_style.inherit(defs, &attrs);
+    let chunks = convert_chunks(defs, fonts, text_path, &style)?;
+
+    Some(dom::TextPath {
+        segments,
+        start_offset,
+        children: chunks,
+    })
+}
+
+/// A polyline approximation of a path together with the cumulative arc length at
+/// each sample point, used to place text along a path by distance.
+struct ArcLengthTable {
+    // (cumulative length, x, y, tangent angle in radians) at each sample point.
+    samples: Vec<(f64, f64, f64, f64)>,
+}
+
+impl ArcLengthTable {
+    /// Number of flattening steps used per curve segment. Good enough for glyph
+    /// placement purposes without pulling in a dedicated curve library.
+    const STEPS_PER_CURVE: u32 = 32;
+
+    fn build(segments: &[dom::PathSegment]) -> ArcLengthTable {
+        let mut samples = Vec::new();
+        let mut cur = (0.0, 0.0);
+        let mut start = (0.0, 0.0);
+        let mut len = 0.0;
+        // A `MoveTo` (the start of a subpath) needs its own sample pushed before the
+        // next segment, so a multi-subpath `<path>` doesn't get bridged by a bogus
+        // interpolated line spanning the gap between subpaths.
+        let mut need_start_sample = true;
+
+        let mut push_point = |
+            len: &mut f64,
+            samples: &mut Vec<(f64, f64, f64, f64)>,
+            need_start_sample: &mut bool,
+            from: (f64, f64),
+            to: (f64, f64),
+        | {
+            let dx = to.0 - from.0;
+            let dy = to.1 - from.1;
+            let seg_len = (dx * dx + dy * dy).sqrt();
+            if seg_len.fuzzy_eq(&0.0) {
+                return;
+            }
+
+            let angle = dy.atan2(dx);
+            if *need_start_sample {
+                samples.push((*len, from.0, from.1, angle));
+                *need_start_sample = false;
+            }
+
+            *len += seg_len;
+            samples.push((*len, to.0, to.1, angle));
+        };
+
+        for seg in segments {
+            match *seg {
+                dom::PathSegment::MoveTo { x, y } => {
+                    cur = (x, y);
+                    start = cur;
+                    need_start_sample = true;
+                }
+                dom::PathSegment::LineTo { x, y } => {
+                    push_point(&mut len, &mut samples, &mut need_start_sample, cur, (x, y));
+                    cur = (x, y);
+                }
+                dom::PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                    let mut prev = cur;
+                    for i in 1..=Self::STEPS_PER_CURVE {
+                        let t = i as f64 / Self::STEPS_PER_CURVE as f64;
+                        let pt = cubic_bezier_at(cur, (x1, y1), (x2, y2), (x, y), t);
+                        push_point(&mut len, &mut samples, &mut need_start_sample, prev, pt);
+                        prev = pt;
+                    }
+                    cur = (x, y);
+                }
+                dom::PathSegment::ClosePath => {
+                    push_point(&mut len, &mut samples, &mut need_start_sample, cur, start);
+                    cur = start;
+                }
+            }
+        }
+
+        ArcLengthTable { samples }
+    }
+
+    fn total_length(&self) -> f64 {
+        self.samples.last().map_or(0.0, |s| s.0)
+    }
+
+    /// Returns the point and tangent angle (radians) at the given distance along
+    /// the path, or `None` if the distance falls outside `[0, total_length]`.
+    fn point_at(&self, distance: f64) -> Option<(f64, f64, f64)> {
+        if distance < 0.0 || distance > self.total_length() || self.samples.len() < 2 {
+            return None;
+        }
+
+        let idx = match self.samples.binary_search_by(|s| s.0.partial_cmp(&distance).unwrap()) {
+            Ok(i) => i.min(self.samples.len() - 2),
+            Err(i) => (i.max(1) - 1).min(self.samples.len() - 2),
+        };
+
+        let (len0, x0, y0, _) = self.samples[idx];
+        let (len1, x1, y1, angle) = self.samples[idx + 1];
+
+        let t = if (len1 - len0).fuzzy_eq(&0.0) { 0.0 } else { (distance - len0) / (len1 - len0) };
+
+        Some((x0 + (x1 - x0) * t, y0 + (y1 - y0) * t, angle))
+    }
+}
+
+fn cubic_bezier_at(p0: (f64, f64), p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), t: f64) -> (f64, f64) {
+    let mt = 1.0 - t;
+    let a = mt * mt * mt;
+    let b = 3.0 * mt * mt * t;
+    let c = 3.0 * mt * t * t;
+    let d = t * t * t;
+
+    (
+        a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+        a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+    )
+}
+
+/// Resolves `startOffset`, honoring both absolute lengths and percentages of the
+/// total path length.
+fn resolve_start_offset(attrs: &svgdom::Attributes, path_length: f64) -> f64 {
+    match attrs.get_length(AId::StartOffset) {
+        Some(ref length) if length.unit == svgdom::LengthUnit::Percent => {
+            path_length * (length.num / 100.0)
+        }
+        Some(ref length) => length.num,
+        None => 0.0,
+    }
+}
+
+/// Per-character layout data resolved from a `tspan`'s `x`/`y`/`dx`/`dy`/`rotate` lists.
+///
+/// Produced alongside a run's text so a later layout stage can place each glyph
+/// without having to re-parse the source attributes.
+#[derive(Clone, Copy, Debug)]
+pub struct CharacterPosition {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub dx: f64,
+    pub dy: f64,
+    pub rotate: f64,
+}
+
 fn convert_chunks(
     defs: &[dom::RefElement],
+    fonts: &FontDatabase,
     text_elem: &svgdom::Node,
+    base_style: &TextStyle,
 ) -> Option<Vec<dom::TextChunk>> {
     let mut chunks = Vec::new();
     let mut tspans = Vec::new();
@@ -56,7 +248,7 @@ fn convert_chunks(
     let mut prev_x = resolve_pos(root_attrs, AId::X).unwrap_or(0.0);
     let mut prev_y = resolve_pos(root_attrs, AId::Y).unwrap_or(0.0);
 
-    let mut first_chunk = text_elem.clone();
+    let mut chunk_node = text_elem.clone();
 
     for tspan in text_elem.children() {
         debug_assert!(tspan.is_tag_name(EId::Tspan));
@@ -67,50 +259,148 @@ fn convert_chunks(
             continue;
         };
 
-
         let ref attrs = tspan.attributes();
-        let x = resolve_pos(attrs, AId::X);
-        let y = resolve_pos(attrs, AId::Y);
+        let positions = resolve_char_positions(attrs, &text);
+        let style = base_style.inherit(defs, attrs);
 
-        if x.is_some() || y.is_some() {
-            let tx = x.unwrap_or(0.0);
-            let ty = y.unwrap_or(0.0);
+        // Split the tspan into runs, each becoming its own `TSpan`. A new run (and
+        // therefore a new text chunk) starts at every character that carries an
+        // absolute `x`, not only at the first character of the tspan.
+        let char_bytes: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
 
-            if !tspans.is_empty() {
-                if tx.fuzzy_ne(&prev_x) || ty.fuzzy_ne(&prev_y) {
-                    chunks.push(create_text_chunk(prev_x, prev_y, &tspans, &first_chunk));
-                    tspans.clear();
-                }
-            }
+        let mut run_start_byte = 0;
+        let mut run_start_char = 0;
+
+        for char_idx in 1..positions.len() {
+            if positions[char_idx].x.is_some() {
+                let byte_idx = char_bytes[char_idx];
+
+                push_run(
+                    &mut chunks, &mut tspans, &mut prev_x, &mut prev_y, &mut chunk_node,
+                    defs, fonts, text_elem, &tspan, &style,
+                    &text[run_start_byte..byte_idx],
+                    &positions[run_start_char..char_idx],
+                    true,
+                );
 
-            prev_x = x.unwrap_or(prev_x);
-            prev_y = y.unwrap_or(prev_y);
-            first_chunk = tspan.clone();
+                run_start_byte = byte_idx;
+                run_start_char = char_idx;
+            }
         }
 
-        tspans.push(dom::TSpan {
-            fill: fill::convert(defs, attrs),
-            stroke: stroke::convert(defs, attrs),
-            font: convert_font(attrs),
-            decoration: conv_tspan_decoration2(defs, text_elem, &tspan),
-            text: text,
-        });
+        let starts_chunk = positions.get(0).map_or(false, |p| p.x.is_some() || p.y.is_some());
+        push_run(
+            &mut chunks, &mut tspans, &mut prev_x, &mut prev_y, &mut chunk_node,
+            defs, fonts, text_elem, &tspan, &style,
+            &text[run_start_byte..],
+            &positions[run_start_char..],
+            starts_chunk,
+        );
     }
 
     if !tspans.is_empty() {
-        chunks.push(create_text_chunk(prev_x, prev_y, &tspans, &first_chunk));
+        chunks.push(create_text_chunk(prev_x, prev_y, &tspans, &chunk_node));
     }
 
     Some(chunks)
 }
 
+/// Converts a single run of text (a whole tspan, or one of the parts a tspan is
+/// split into at each mid-tspan absolute `x`) into a `dom::TSpan`, starting a new
+/// chunk first if needed.
+fn push_run(
+    chunks: &mut Vec<dom::TextChunk>,
+    tspans: &mut Vec<dom::TSpan>,
+    prev_x: &mut f64,
+    prev_y: &mut f64,
+    chunk_node: &mut svgdom::Node,
+    defs: &[dom::RefElement],
+    fonts: &FontDatabase,
+    text_elem: &svgdom::Node,
+    tspan: &svgdom::Node,
+    style: &TextStyle,
+    text: &str,
+    positions: &[CharacterPosition],
+    force_new_chunk: bool,
+) {
+    if text.is_empty() {
+        return;
+    }
+
+    if force_new_chunk {
+        let tx = positions[0].x.unwrap_or(*prev_x);
+        let ty = positions[0].y.unwrap_or(*prev_y);
+
+        if !tspans.is_empty() && (positions[0].x.is_some() || tx.fuzzy_ne(prev_x) || ty.fuzzy_ne(prev_y)) {
+            chunks.push(create_text_chunk(*prev_x, *prev_y, tspans, chunk_node));
+            tspans.clear();
+        }
+
+        *prev_x = tx;
+        *prev_y = ty;
+        *chunk_node = tspan.clone();
+    }
+
+    let font_id = fonts.select_face(&style.font);
+
+    tspans.push(dom::TSpan {
+        fill: style.fill.clone(),
+        stroke: style.stroke.clone(),
+        font: style.font.clone(),
+        font_id,
+        decoration: conv_tspan_decoration2(defs, text_elem, tspan),
+        text: text.to_string(),
+        positions: positions.to_vec(),
+        baseline_shift: style.baseline_shift,
+    });
+}
+
+/// Builds the per-character position list for a tspan's text from its
+/// `x`/`y`/`dx`/`dy`/`rotate` number lists.
+///
+/// `x`/`y` address characters by index (a missing trailing entry leaves the position
+/// unset, so the character falls back to the running pen position). `dx`/`dy` are
+/// per-character relative shifts. `rotate` is per-character, and once exhausted the
+/// last value persists for the remaining characters.
+fn resolve_char_positions(attrs: &svgdom::Attributes, text: &str) -> Vec<CharacterPosition> {
+    let x_list = resolve_number_list(attrs, AId::X);
+    let y_list = resolve_number_list(attrs, AId::Y);
+    let dx_list = resolve_number_list(attrs, AId::Dx);
+    let dy_list = resolve_number_list(attrs, AId::Dy);
+    let rotate_list = resolve_number_list(attrs, AId::Rotate);
+
+    let char_count = text.chars().count();
+    let mut positions = Vec::with_capacity(char_count);
+
+    for i in 0..char_count {
+        let rotate = if i < rotate_list.len() {
+            rotate_list[i]
+        } else {
+            rotate_list.last().cloned().unwrap_or(0.0)
+        };
+
+        positions.push(CharacterPosition {
+            x: x_list.get(i).cloned(),
+            y: y_list.get(i).cloned(),
+            dx: dx_list.get(i).cloned().unwrap_or(0.0),
+            dy: dy_list.get(i).cloned().unwrap_or(0.0),
+            rotate,
+        });
+    }
+
+    positions
+}
+
+fn resolve_number_list(attrs: &svgdom::Attributes, aid: AId) -> Vec<f64> {
+    attrs.get_number_list(aid).map(|list| list.to_vec()).unwrap_or_default()
+}
+
 fn resolve_pos(attrs: &svgdom::Attributes, aid: AId) -> Option<f64> {
+    // Only the first entry is used here: this helper resolves the element-level
+    // starting pen position. Per-character list handling is done in
+    // `resolve_char_positions`.
     if let Some(ref list) = attrs.get_number_list(aid) {
         if !list.is_empty() {
-            if list.len() > 1 {
-                warn!("List of 'x', 'y' coordinates are not supported in a 'text' element.");
-            }
-
             return Some(list[0]);
         }
     }
@@ -198,8 +488,8 @@ fn conv_tspan_decoration2(
         };
 
         let ref attrs = n.attributes();
-        let fill = fill::convert(defs, attrs);
-        let stroke = stroke::convert(defs, attrs);
+        let fill = fill::convert(defs, attrs, None);
+        let stroke = stroke::convert(defs, attrs, None);
 
         Some(dom::TextDecorationStyle {
             fill,
@@ -225,62 +515,67 @@ fn conv_text_anchor(attrs: &svgdom::Attributes) -> dom::TextAnchor {
     }
 }
 
-fn convert_font(attrs: &svgdom::Attributes) -> dom::Font {
-    let style = attrs.get_predef(AId::FontStyle).unwrap_or(svgdom::ValueId::Normal);
-    let style = match style {
-        svgdom::ValueId::Normal => dom::FontStyle::Normal,
-        svgdom::ValueId::Italic => dom::FontStyle::Italic,
-        svgdom::ValueId::Oblique => dom::FontStyle::Oblique,
-        _ => dom::FontStyle::Normal,
+/// Resolves a tspan's font from its own attributes, falling back to `parent` for
+/// every property that isn't explicitly set on this element - i.e. font properties
+/// are additive and inherit down the text/tspan tree rather than resetting to a
+/// hardcoded default.
+fn convert_font(attrs: &svgdom::Attributes, parent: &dom::Font) -> dom::Font {
+    let style = match attrs.get_predef(AId::FontStyle) {
+        Some(svgdom::ValueId::Normal) => dom::FontStyle::Normal,
+        Some(svgdom::ValueId::Italic) => dom::FontStyle::Italic,
+        Some(svgdom::ValueId::Oblique) => dom::FontStyle::Oblique,
+        _ => parent.style,
     };
 
-    let variant = attrs.get_predef(AId::FontVariant).unwrap_or(svgdom::ValueId::Normal);
-    let variant = match variant {
-        svgdom::ValueId::Normal => dom::FontVariant::Normal,
-        svgdom::ValueId::SmallCaps => dom::FontVariant::SmallCaps,
-        _ => dom::FontVariant::Normal,
+    let variant = match attrs.get_predef(AId::FontVariant) {
+        Some(svgdom::ValueId::Normal) => dom::FontVariant::Normal,
+        Some(svgdom::ValueId::SmallCaps) => dom::FontVariant::SmallCaps,
+        _ => parent.variant,
     };
 
-    let weight = attrs.get_predef(AId::FontWeight).unwrap_or(svgdom::ValueId::Normal);
-    let weight = match weight {
-        svgdom::ValueId::Normal => dom::FontWeight::Normal,
-        svgdom::ValueId::Bold => dom::FontWeight::Bold,
-        svgdom::ValueId::Bolder => dom::FontWeight::Bolder,
-        svgdom::ValueId::Lighter => dom::FontWeight::Lighter,
-        svgdom::ValueId::N100 => dom::FontWeight::W100,
-        svgdom::ValueId::N200 => dom::FontWeight::W200,
-        svgdom::ValueId::N300 => dom::FontWeight::W300,
-        svgdom::ValueId::N400 => dom::FontWeight::W400,
-        svgdom::ValueId::N500 => dom::FontWeight::W500,
-        svgdom::ValueId::N600 => dom::FontWeight::W600,
-        svgdom::ValueId::N700 => dom::FontWeight::W700,
-        svgdom::ValueId::N800 => dom::FontWeight::W800,
-        svgdom::ValueId::N900 => dom::FontWeight::W900,
-        _ => dom::FontWeight::Normal,
+    let weight = match attrs.get_predef(AId::FontWeight) {
+        Some(svgdom::ValueId::Normal) => dom::FontWeight::Normal,
+        Some(svgdom::ValueId::Bold) => dom::FontWeight::Bold,
+        Some(svgdom::ValueId::Bolder) => dom::FontWeight::Bolder,
+        Some(svgdom::ValueId::Lighter) => dom::FontWeight::Lighter,
+        Some(svgdom::ValueId::N100) => dom::FontWeight::W100,
+        Some(svgdom::ValueId::N200) => dom::FontWeight::W200,
+        Some(svgdom::ValueId::N300) => dom::FontWeight::W300,
+        Some(svgdom::ValueId::N400) => dom::FontWeight::W400,
+        Some(svgdom::ValueId::N500) => dom::FontWeight::W500,
+        Some(svgdom::ValueId::N600) => dom::FontWeight::W600,
+        Some(svgdom::ValueId::N700) => dom::FontWeight::W700,
+        Some(svgdom::ValueId::N800) => dom::FontWeight::W800,
+        Some(svgdom::ValueId::N900) => dom::FontWeight::W900,
+        _ => parent.weight,
     };
 
-    let stretch = attrs.get_predef(AId::FontStretch).unwrap_or(svgdom::ValueId::Normal);
-    let stretch = match stretch {
-        svgdom::ValueId::Normal => dom::FontStretch::Normal,
-        svgdom::ValueId::Wider => dom::FontStretch::Wider,
-        svgdom::ValueId::Narrower => dom::FontStretch::Narrower,
-        svgdom::ValueId::UltraCondensed => dom::FontStretch::UltraCondensed,
-        svgdom::ValueId::ExtraCondensed => dom::FontStretch::ExtraCondensed,
-        svgdom::ValueId::Condensed => dom::FontStretch::Condensed,
-        svgdom::ValueId::SemiCondensed => dom::FontStretch::SemiCondensed,
-        svgdom::ValueId::SemiExpanded => dom::FontStretch::SemiExpanded,
-        svgdom::ValueId::Expanded => dom::FontStretch::Expanded,
-        svgdom::ValueId::ExtraExpanded => dom::FontStretch::ExtraExpanded,
-        svgdom::ValueId::UltraExpanded => dom::FontStretch::UltraExpanded,
-        _ => dom::FontStretch::Normal,
+    let stretch = match attrs.get_predef(AId::FontStretch) {
+        Some(svgdom::ValueId::Normal) => dom::FontStretch::Normal,
+        Some(svgdom::ValueId::Wider) => dom::FontStretch::Wider,
+        Some(svgdom::ValueId::Narrower) => dom::FontStretch::Narrower,
+        Some(svgdom::ValueId::UltraCondensed) => dom::FontStretch::UltraCondensed,
+        Some(svgdom::ValueId::ExtraCondensed) => dom::FontStretch::ExtraCondensed,
+        Some(svgdom::ValueId::Condensed) => dom::FontStretch::Condensed,
+        Some(svgdom::ValueId::SemiCondensed) => dom::FontStretch::SemiCondensed,
+        Some(svgdom::ValueId::SemiExpanded) => dom::FontStretch::SemiExpanded,
+        Some(svgdom::ValueId::Expanded) => dom::FontStretch::Expanded,
+        Some(svgdom::ValueId::ExtraExpanded) => dom::FontStretch::ExtraExpanded,
+        Some(svgdom::ValueId::UltraExpanded) => dom::FontStretch::UltraExpanded,
+        _ => parent.stretch,
     };
 
     // TODO: remove text nodes with font-size <= 0
-    let size = attrs.get_number(AId::FontSize).unwrap_or(::DEFAULT_FONT_SIZE);
+    let size = attrs.get_number(AId::FontSize).unwrap_or(parent.size);
     debug_assert!(size > 0.0);
 
     let family = attrs.get_string(AId::FontFamily)
-                      .unwrap_or(&::DEFAULT_FONT_FAMILY.to_owned()).clone();
+                      .map(|s| parse_font_family(s))
+                      .filter(|list| !list.is_empty())
+                      .unwrap_or_else(|| parent.family.clone());
+
+    let letter_spacing = attrs.get_number(AId::LetterSpacing).unwrap_or(parent.letter_spacing);
+    let word_spacing = attrs.get_number(AId::WordSpacing).unwrap_or(parent.word_spacing);
 
     dom::Font {
         family,
@@ -289,5 +584,711 @@ fn convert_font(attrs: &svgdom::Attributes) -> dom::Font {
         variant,
         weight,
         stretch,
+        letter_spacing,
+        word_spacing,
+    }
+}
+
+/// The font/fill/stroke resolved so far while walking down the text/tspan tree.
+/// Threaded from `text` through `textPath` to each `tspan`, so a property absent
+/// on a given element inherits the ancestor's computed value instead of a global
+/// default.
+///
+/// `baseline_shift` is the cumulative offset (in user units, positive = upward)
+/// from the `text` element's own baseline: unlike the font/paint properties, each
+/// element's `baseline-shift` is relative to its *parent's* (possibly already
+/// shifted) baseline, so the resolved amounts accumulate down the tree instead of
+/// simply being overridden.
+#[derive(Clone)]
+struct TextStyle {
+    font: dom::Font,
+    fill: Option<dom::Fill>,
+    stroke: Option<dom::Stroke>,
+    baseline_shift: f64,
+}
+
+impl TextStyle {
+    /// Builds the style at the root of a `text` element, starting from the crate's
+    /// global text defaults.
+    fn root(defs: &[dom::RefElement], attrs: &svgdom::Attributes) -> TextStyle {
+        let default_font = dom::Font {
+            family: vec![::DEFAULT_FONT_FAMILY.to_string()],
+            size: ::DEFAULT_FONT_SIZE,
+            style: dom::FontStyle::Normal,
+            variant: dom::FontVariant::Normal,
+            weight: dom::FontWeight::Normal,
+            stretch: dom::FontStretch::Normal,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+        };
+
+        let font = convert_font(attrs, &default_font);
+
+        TextStyle {
+            baseline_shift: resolve_baseline_shift(attrs, font.size),
+            font,
+            fill: fill::convert(defs, attrs, None),
+            stroke: stroke::convert(defs, attrs, None),
+        }
+    }
+
+    /// Resolves a descendant element's style, overriding only the properties it
+    /// explicitly sets.
+    fn inherit(&self, defs: &[dom::RefElement], attrs: &svgdom::Attributes) -> TextStyle {
+        let font = convert_font(attrs, &self.font);
+
+        TextStyle {
+            baseline_shift: self.baseline_shift + resolve_baseline_shift(attrs, font.size),
+            font,
+            fill: fill::convert(defs, attrs, self.fill.clone()),
+            stroke: stroke::convert(defs, attrs, self.stroke.clone()),
+        }
+    }
+}
+
+/// Resolves `baseline-shift` (a length, a percentage of the font size, or the
+/// `sub`/`super` keywords) together with `alignment-baseline`/`dominant-baseline`
+/// (`alignment-baseline` wins when both are set, matching its role as the more
+/// specific override), combining them into a single upward offset relative to the
+/// inherited baseline. The keyword presets are approximated as fractions of the
+/// font size, since the actual baseline tables live in the font and aren't
+/// available until the shaping stage.
+fn resolve_baseline_shift(attrs: &svgdom::Attributes, font_size: f64) -> f64 {
+    let mut shift = match attrs.get_predef(AId::BaselineShift) {
+        Some(svgdom::ValueId::Sub) => -0.2 * font_size,
+        Some(svgdom::ValueId::Super) => 0.4 * font_size,
+        Some(svgdom::ValueId::Baseline) => 0.0,
+        _ => match attrs.get_length(AId::BaselineShift) {
+            Some(ref length) if length.unit == svgdom::LengthUnit::Percent => {
+                font_size * (length.num / 100.0)
+            }
+            Some(ref length) => length.num,
+            None => 0.0,
+        },
+    };
+
+    let baseline = attrs.get_predef(AId::AlignmentBaseline)
+        .or_else(|| attrs.get_predef(AId::DominantBaseline));
+
+    shift += match baseline {
+        Some(svgdom::ValueId::Middle) | Some(svgdom::ValueId::Central) => 0.25 * font_size,
+        Some(svgdom::ValueId::Hanging) | Some(svgdom::ValueId::TextBeforeEdge) => 0.8 * font_size,
+        Some(svgdom::ValueId::TextAfterEdge) => -0.2 * font_size,
+        _ => 0.0,
+    };
+
+    shift
+}
+
+/// Parses a `font-family` value into a prioritized list of names, unquoting each one.
+fn parse_font_family(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty())
+        .map(unquote_family)
+        .collect()
+}
+
+fn unquote_family(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let is_quoted = bytes.len() >= 2
+        && ((bytes[0] == b'\'' && bytes[bytes.len() - 1] == b'\'')
+            || (bytes[0] == b'"' && bytes[bytes.len() - 1] == b'"'));
+
+    if is_quoted {
+        name[1..name.len() - 1].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// The known CSS generic font-family keywords.
+const GENERIC_FONT_FAMILIES: &[&str] = &["serif", "sans-serif", "monospace", "cursive", "fantasy"];
+
+/// A face available for text shaping, as enumerated from the system font database.
+pub struct FaceInfo {
+    pub id: FaceId,
+    pub family: String,
+    pub style: dom::FontStyle,
+    pub weight: dom::FontWeight,
+    pub stretch: dom::FontStretch,
+    pub source: FaceSource,
+}
+
+pub type FaceId = usize;
+
+/// The raw font data backing a `FaceInfo`, as needed to shape text and extract
+/// glyph outlines (a font file may contain more than one face, hence `index`).
+pub struct FaceSource {
+    pub data: Vec<u8>,
+    pub index: u32,
+}
+
+/// Resolves a `dom::Font` (a prioritized family list plus style/weight/stretch) down
+/// to a concrete face, modeled on usvg's `fontdb`.
+pub struct FontDatabase {
+    faces: Vec<FaceInfo>,
+}
+
+impl FontDatabase {
+    pub fn new(faces: Vec<FaceInfo>) -> FontDatabase {
+        FontDatabase { faces }
+    }
+
+    /// Tries each requested family in order, mapping generic keywords to a platform
+    /// default, and finally falls back to `DEFAULT_FONT_FAMILY`.
+    pub fn select_face(&self, font: &dom::Font) -> Option<FaceId> {
+        for family in &font.family {
+            let resolved = if is_generic_family(family) {
+                match_generic_family(family)
+            } else {
+                Some(family.clone())
+            };
+
+            if let Some(ref name) = resolved {
+                if let Some(id) = self.find_face(name, font) {
+                    return Some(id);
+                }
+            }
+        }
+
+        self.find_face(::DEFAULT_FONT_FAMILY, font)
+    }
+
+    /// Returns the raw font data for a previously resolved face id.
+    pub fn face_source(&self, id: FaceId) -> Option<&FaceSource> {
+        self.faces.iter().find(|f| f.id == id).map(|f| &f.source)
+    }
+
+    fn find_face(&self, family: &str, font: &dom::Font) -> Option<FaceId> {
+        self.faces.iter()
+            .filter(|f| f.family.eq_ignore_ascii_case(family))
+            .min_by_key(|f| face_distance(f, font))
+            .map(|f| f.id)
+    }
+}
+
+/// A cache of parsed `ttf_parser::Face`s keyed by `FaceId`, shared across coverage
+/// probing and shaping so a face already probed for coverage (or already used to
+/// shape an earlier run) isn't re-parsed from scratch.
+type FaceCache<'a> = HashMap<FaceId, ttf_parser::Face<'a>>;
+
+/// Returns the parsed face for `id`, parsing and caching it on first use.
+fn cached_face<'c, 'a>(fonts: &'a FontDatabase, cache: &'c mut FaceCache<'a>, id: FaceId) -> Option<&'c ttf_parser::Face<'a>> {
+    if !cache.contains_key(&id) {
+        let source = fonts.face_source(id)?;
+        let face = ttf_parser::Face::from_slice(&source.data, source.index).ok()?;
+        cache.insert(id, face);
+    }
+
+    cache.get(&id)
+}
+
+fn face_has_glyph<'a>(fonts: &'a FontDatabase, cache: &mut FaceCache<'a>, id: FaceId, ch: char) -> bool {
+    cached_face(fonts, cache, id).map_or(false, |face| face.glyph_index(ch).is_some())
+}
+
+/// Returns `face` itself if it has a glyph for `ch`, otherwise searches the
+/// database for the closest-matching face (by requested weight/stretch/style)
+/// that does, so a run can fall back instead of rendering a `.notdef` box.
+fn face_for_char<'a>(
+    fonts: &'a FontDatabase,
+    cache: &mut FaceCache<'a>,
+    face: FaceId,
+    font: &dom::Font,
+    ch: char,
+) -> FaceId {
+    if face_has_glyph(fonts, cache, face, ch) {
+        return face;
+    }
+
+    fonts.faces.iter()
+        .filter(|f| f.id != face && face_has_glyph(fonts, cache, f.id, ch))
+        .min_by_key(|f| face_distance(f, font))
+        .map_or(face, |f| f.id)
+}
+
+fn is_generic_family(name: &str) -> bool {
+    GENERIC_FONT_FAMILIES.iter().any(|f| f.eq_ignore_ascii_case(name))
+}
+
+/// Maps a generic family keyword (`serif`, `sans-serif`, ...) to a concrete platform
+/// default family name, using `fc-match` on Linux.
+#[cfg(target_os = "linux")]
+fn match_generic_family(generic: &str) -> Option<String> {
+    use std::process::Command;
+
+    let output = Command::new("fc-match")
+        .args(&["-f", "%{family}", &generic.to_ascii_lowercase()])
+        .output()
+        .ok()?;
+
+    let name = String::from_utf8(output.stdout).ok()?;
+    let name = name.trim();
+
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn match_generic_family(_generic: &str) -> Option<String> {
+    None
+}
+
+fn face_distance(face: &FaceInfo, font: &dom::Font) -> u32 {
+    let style_penalty = if face.style == font.style { 0 } else { 1000 };
+    let stretch_penalty = if face.stretch == font.stretch { 0 } else { 500 };
+    let weight_penalty = (weight_value(face.weight) - weight_value(font.weight)).abs() as u32;
+
+    style_penalty + stretch_penalty + weight_penalty
+}
+
+fn weight_value(weight: dom::FontWeight) -> i32 {
+    match weight {
+        dom::FontWeight::W100 => 100,
+        dom::FontWeight::W200 => 200,
+        dom::FontWeight::W300 => 300,
+        dom::FontWeight::Lighter => 300,
+        dom::FontWeight::W400 | dom::FontWeight::Normal => 400,
+        dom::FontWeight::W500 => 500,
+        dom::FontWeight::W600 => 600,
+        dom::FontWeight::W700 | dom::FontWeight::Bold => 700,
+        dom::FontWeight::Bolder => 800,
+        dom::FontWeight::W800 => 800,
+        dom::FontWeight::W900 => 900,
+    }
+}
+
+/// Replaces every `Text`/`TextPath` element in the tree with a `Group` of `Path`
+/// elements, so the result renders identically without a system text stack at
+/// paint time.
+pub fn convert_text_to_paths(tree: &mut dom::Tree, fonts: &FontDatabase) {
+    for element in tree.elements_mut() {
+        let paths = match element.kind {
+            dom::ElementKind::Text(ref text) => shape_text(&text.children, None, fonts),
+            dom::ElementKind::TextPath(ref text_path) => {
+                let arc_length = ArcLengthTable::build(&text_path.segments);
+                shape_text(&text_path.children, Some((&arc_length, text_path.start_offset)), fonts)
+            }
+            _ => continue,
+        };
+
+        element.kind = dom::ElementKind::Group(dom::Group {
+            children: paths.into_iter().map(|path| dom::Element {
+                id: String::new(),
+                kind: dom::ElementKind::Path(path),
+                transform: svgdom::types::Transform::default(),
+            }).collect(),
+        });
+    }
+}
+
+/// A single shaped glyph: its outline (already scaled to font-size units), the
+/// resolved per-character position of the character that produced it, and the
+/// face it was actually shaped with (which may be a fallback face, see `build_runs`).
+struct ShapedGlyph {
+    outline: Vec<dom::PathSegment>,
+    advance: f64,
+    position: CharacterPosition,
+    face_id: FaceId,
+}
+
+/// Decoration-relevant metrics of a face, scaled to the run's font size.
+struct FontMetrics {
+    underline_y: f64,
+    underline_thickness: f64,
+    strikeout_y: f64,
+    overline_y: f64,
+}
+
+fn shape_text(
+    chunks: &[dom::TextChunk],
+    path_layout: Option<(&ArcLengthTable, f64)>,
+    fonts: &FontDatabase,
+) -> Vec<dom::Path> {
+    let mut paths = Vec::new();
+    let mut path_distance = path_layout.map_or(0.0, |(_, offset)| offset);
+    let mut face_cache = FaceCache::new();
+
+    for chunk in chunks {
+        let mut runs: Vec<(&dom::TSpan, Vec<ShapedGlyph>, FontMetrics)> = Vec::new();
+        let mut total_width = 0.0;
+
+        for tspan in &chunk.children {
+            if tspan.font_id.is_none() {
+                continue;
+            }
+
+            let char_bytes: Vec<usize> = tspan.text.char_indices().map(|(i, _)| i).collect();
+
+            for (start, end, face_id) in build_runs(tspan, fonts, &mut face_cache) {
+                let source = match fonts.face_source(face_id) {
+                    Some(s) => s,
+                    None => continue,
+                };
+
+                let byte_start = char_bytes.get(start).cloned().unwrap_or_else(|| tspan.text.len());
+                let byte_end = char_bytes.get(end).cloned().unwrap_or_else(|| tspan.text.len());
+                let positions = &tspan.positions[start.min(tspan.positions.len())..end.min(tspan.positions.len())];
+
+                if let Some((glyphs, metrics)) = shape_run(
+                    &tspan.text[byte_start..byte_end],
+                    source,
+                    face_id,
+                    fonts,
+                    &mut face_cache,
+                    tspan.font.size,
+                    tspan.font.letter_spacing,
+                    tspan.font.word_spacing,
+                    positions,
+                ) {
+                    total_width += glyphs.iter().map(|g| g.advance).sum::<f64>();
+                    runs.push((tspan, glyphs, metrics));
+                }
+            }
+        }
+
+        let mut pen_x = chunk.x;
+        let mut pen_y = chunk.y;
+
+        // The anchor shifts the whole chunk relative to its nominal origin, so it
+        // must also apply to characters with an explicit absolute `x` - not just to
+        // `pen_x`, the fallback used by characters without one.
+        let mut anchor_offset = 0.0;
+
+        if path_layout.is_none() {
+            anchor_offset = match chunk.anchor {
+                dom::TextAnchor::Start => 0.0,
+                dom::TextAnchor::Middle => -total_width / 2.0,
+                dom::TextAnchor::End => -total_width,
+            };
+            pen_x += anchor_offset;
+        }
+
+        for (tspan, glyphs, metrics) in &runs {
+            let run_start_x = pen_x;
+
+            for glyph in glyphs {
+                let (x, y, rotate) = if let Some((arc, _)) = path_layout {
+                    let origin = path_distance;
+                    let d = path_distance + glyph.advance / 2.0;
+                    path_distance += glyph.advance;
+
+                    // The rotation is taken at the glyph's midpoint, but the glyph
+                    // outline's local origin (x=0, same convention as the non-path
+                    // branch) is placed at the left-edge distance, not the midpoint.
+                    let angle = match arc.point_at(d) {
+                        Some((_, _, angle)) => angle,
+                        // Glyphs whose midpoint falls outside the path are dropped.
+                        None => continue,
+                    };
+
+                    match arc.point_at(origin) {
+                        // Offset perpendicular to the tangent (the normal, rotated
+                        // -90°), not straight up in world space, so the shift tracks
+                        // the path's direction on non-horizontal stretches.
+                        Some((px, py, tangent)) => {
+                            let nx = tangent.sin();
+                            let ny = -tangent.cos();
+                            (
+                                px + nx * tspan.baseline_shift,
+                                py + ny * tspan.baseline_shift,
+                                angle.to_degrees(),
+                            )
+                        }
+                        None => continue,
+                    }
+                } else {
+                    let gx = glyph.position.x.map_or(pen_x, |v| v + anchor_offset) + glyph.position.dx;
+                    let base_y = glyph.position.y.unwrap_or(pen_y) + glyph.position.dy;
+                    pen_x = gx + glyph.advance;
+                    pen_y = base_y;
+                    (gx, base_y - tspan.baseline_shift, glyph.position.rotate)
+                };
+
+                let outline = place_glyph_outline(&glyph.outline, x, y, rotate);
+
+                paths.push(dom::Path {
+                    fill: tspan.fill.clone(),
+                    stroke: tspan.stroke.clone(),
+                    segments: outline,
+                });
+            }
+
+            if path_layout.is_none() {
+                paths.extend(decoration_rects(tspan, metrics, run_start_x, pen_x, pen_y));
+            }
+        }
+    }
+
+    paths
+}
+
+/// Shapes one script/face run of text with rustybuzz and extracts each resulting
+/// glyph's outline from the font via ttf-parser.
+fn shape_run<'a>(
+    text: &str,
+    source: &FaceSource,
+    face_id: FaceId,
+    fonts: &'a FontDatabase,
+    cache: &mut FaceCache<'a>,
+    font_size: f64,
+    letter_spacing: f64,
+    word_spacing: f64,
+    positions: &[CharacterPosition],
+) -> Option<(Vec<ShapedGlyph>, FontMetrics)> {
+    if text.is_empty() {
+        return None;
+    }
+
+    let rb_face = rustybuzz::Face::from_slice(&source.data, source.index)?;
+    let ttf_face = cached_face(fonts, cache, face_id)?;
+
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+
+    let glyph_buffer = rustybuzz::shape(&rb_face, &[], buffer);
+
+    let units_per_em = f64::from(ttf_face.units_per_em());
+    let scale = font_size / units_per_em;
+
+    let mut glyphs = Vec::with_capacity(glyph_buffer.len());
+    for (info, pos) in glyph_buffer.glyph_infos().iter().zip(glyph_buffer.glyph_positions()) {
+        let glyph_id = ttf_parser::GlyphId(info.glyph_id as u16);
+
+        let mut builder = GlyphOutlineBuilder::new();
+        ttf_face.outline_glyph(glyph_id, &mut builder);
+
+        // `cluster` maps the glyph back to the byte offset of the character that
+        // produced it, used to look up its resolved per-character position.
+        let char_idx = text[..info.cluster as usize].chars().count();
+        let position = positions.get(char_idx).cloned().unwrap_or(CharacterPosition {
+            x: None,
+            y: None,
+            dx: 0.0,
+            dy: 0.0,
+            rotate: 0.0,
+        });
+
+        // `letter-spacing` is added after every glyph; `word-spacing` only after
+        // the glyphs produced by a space character.
+        let mut advance = f64::from(pos.x_advance) * scale + letter_spacing;
+        if text[info.cluster as usize..].starts_with(' ') {
+            advance += word_spacing;
+        }
+
+        glyphs.push(ShapedGlyph {
+            outline: builder.segments.into_iter().map(|seg| scale_segment(seg, scale)).collect(),
+            advance,
+            position,
+            face_id,
+        });
+    }
+
+    let metrics = FontMetrics {
+        underline_y: ttf_face.underline_metrics().map_or(0.0, |m| -f64::from(m.position) * scale),
+        underline_thickness: ttf_face.underline_metrics()
+            .map_or(font_size / 14.0, |m| f64::from(m.thickness) * scale),
+        strikeout_y: ttf_face.strikeout_metrics().map_or(0.0, |m| -f64::from(m.position) * scale),
+        overline_y: -f64::from(ttf_face.ascender()) * scale,
+    };
+
+    Some((glyphs, metrics))
+}
+
+/// Groups `text` into maximal runs that share a single Unicode script, so a
+/// shaping call never mixes scripts. `Common`/`Inherited` characters (spaces,
+/// punctuation, combining marks) extend whichever run they fall in.
+fn script_runs(text: &str) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut run_start_char = 0;
+    let mut run_script = None;
+
+    for (char_idx, ch) in text.chars().enumerate() {
+        let mut script = ch.script();
+        if script == unicode_script::Script::Common || script == unicode_script::Script::Inherited {
+            if let Some(s) = run_script {
+                script = s;
+            }
+        }
+
+        match run_script {
+            Some(s) if s == script => {}
+            Some(_) => {
+                runs.push((run_start_char, char_idx));
+                run_start_char = char_idx;
+                run_script = Some(script);
+            }
+            None => run_script = Some(script),
+        }
+    }
+
+    runs.push((run_start_char, text.chars().count()));
+    runs
+}
+
+/// Segments a tspan's text into runs that each share a single Unicode script and a
+/// single face, redirecting characters the script's nominal face can't render to
+/// the closest-matching face in the database that can. Returns `(start, end, face)`
+/// char-index ranges covering the whole text.
+fn build_runs<'a>(tspan: &dom::TSpan, fonts: &'a FontDatabase, cache: &mut FaceCache<'a>) -> Vec<(usize, usize, FaceId)> {
+    let primary = match tspan.font_id {
+        Some(id) => id,
+        None => return Vec::new(),
+    };
+
+    let chars: Vec<char> = tspan.text.chars().collect();
+    let mut runs = Vec::new();
+
+    for (script_start, script_end) in script_runs(&tspan.text) {
+        let mut run_start = script_start;
+        let mut run_face = primary;
+
+        for char_idx in script_start..script_end {
+            let face = face_for_char(fonts, cache, primary, &tspan.font, chars[char_idx]);
+
+            if face != run_face && char_idx > run_start {
+                runs.push((run_start, char_idx, run_face));
+                run_start = char_idx;
+            }
+
+            run_face = face;
+        }
+
+        runs.push((run_start, script_end, run_face));
+    }
+
+    runs
+}
+
+/// Builds a glyph outline from the font's outline commands, in font units.
+struct GlyphOutlineBuilder {
+    segments: Vec<dom::PathSegment>,
+    last: (f32, f32),
+}
+
+impl GlyphOutlineBuilder {
+    fn new() -> Self {
+        GlyphOutlineBuilder { segments: Vec::new(), last: (0.0, 0.0) }
+    }
+}
+
+impl ttf_parser::OutlineBuilder for GlyphOutlineBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.segments.push(dom::PathSegment::MoveTo { x: f64::from(x), y: f64::from(-y) });
+        self.last = (x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.segments.push(dom::PathSegment::LineTo { x: f64::from(x), y: f64::from(-y) });
+        self.last = (x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        // Elevate the quadratic Bezier to the cubic form `dom::PathSegment` expects.
+        let (x0, y0) = self.last;
+        let c1 = (x0 + 2.0 / 3.0 * (x1 - x0), y0 + 2.0 / 3.0 * (y1 - y0));
+        let c2 = (x + 2.0 / 3.0 * (x1 - x), y + 2.0 / 3.0 * (y1 - y));
+
+        self.segments.push(dom::PathSegment::CurveTo {
+            x1: f64::from(c1.0), y1: f64::from(-c1.1),
+            x2: f64::from(c2.0), y2: f64::from(-c2.1),
+            x: f64::from(x), y: f64::from(-y),
+        });
+        self.last = (x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.segments.push(dom::PathSegment::CurveTo {
+            x1: f64::from(x1), y1: f64::from(-y1),
+            x2: f64::from(x2), y2: f64::from(-y2),
+            x: f64::from(x), y: f64::from(-y),
+        });
+        self.last = (x, y);
+    }
+
+    fn close(&mut self) {
+        self.segments.push(dom::PathSegment::ClosePath);
+    }
+}
+
+fn scale_segment(seg: dom::PathSegment, scale: f64) -> dom::PathSegment {
+    match seg {
+        dom::PathSegment::MoveTo { x, y } => dom::PathSegment::MoveTo { x: x * scale, y: y * scale },
+        dom::PathSegment::LineTo { x, y } => dom::PathSegment::LineTo { x: x * scale, y: y * scale },
+        dom::PathSegment::CurveTo { x1, y1, x2, y2, x, y } => dom::PathSegment::CurveTo {
+            x1: x1 * scale, y1: y1 * scale,
+            x2: x2 * scale, y2: y2 * scale,
+            x: x * scale, y: y * scale,
+        },
+        dom::PathSegment::ClosePath => dom::PathSegment::ClosePath,
+    }
+}
+
+/// Rotates (by `rotate` degrees, clockwise in SVG's y-down space) and translates a
+/// glyph outline into its final position.
+fn place_glyph_outline(outline: &[dom::PathSegment], x: f64, y: f64, rotate: f64) -> Vec<dom::PathSegment> {
+    let (sin, cos) = rotate.to_radians().sin_cos();
+
+    let place = |px: f64, py: f64| (px * cos - py * sin + x, px * sin + py * cos + y);
+
+    outline.iter().map(|seg| match *seg {
+        dom::PathSegment::MoveTo { x: gx, y: gy } => {
+            let (nx, ny) = place(gx, gy);
+            dom::PathSegment::MoveTo { x: nx, y: ny }
+        }
+        dom::PathSegment::LineTo { x: gx, y: gy } => {
+            let (nx, ny) = place(gx, gy);
+            dom::PathSegment::LineTo { x: nx, y: ny }
+        }
+        dom::PathSegment::CurveTo { x1, y1, x2, y2, x: gx, y: gy } => {
+            let (nx1, ny1) = place(x1, y1);
+            let (nx2, ny2) = place(x2, y2);
+            let (nx, ny) = place(gx, gy);
+            dom::PathSegment::CurveTo { x1: nx1, y1: ny1, x2: nx2, y2: ny2, x: nx, y: ny }
+        }
+        dom::PathSegment::ClosePath => dom::PathSegment::ClosePath,
+    }).collect()
+}
+
+/// Builds filled rectangles for a run's underline/overline/line-through, sized and
+/// positioned from the face's own metrics.
+fn decoration_rects(
+    tspan: &dom::TSpan,
+    metrics: &FontMetrics,
+    start_x: f64,
+    end_x: f64,
+    baseline_y: f64,
+) -> Vec<dom::Path> {
+    let mut rects = Vec::new();
+
+    if let Some(ref style) = tspan.decoration.underline {
+        rects.push(rect_path(start_x, end_x, baseline_y + metrics.underline_y, metrics.underline_thickness, style));
+    }
+
+    if let Some(ref style) = tspan.decoration.overline {
+        rects.push(rect_path(start_x, end_x, baseline_y + metrics.overline_y, metrics.underline_thickness, style));
+    }
+
+    if let Some(ref style) = tspan.decoration.line_through {
+        rects.push(rect_path(start_x, end_x, baseline_y + metrics.strikeout_y, metrics.underline_thickness, style));
+    }
+
+    rects
+}
+
+fn rect_path(x0: f64, x1: f64, y: f64, thickness: f64, style: &dom::TextDecorationStyle) -> dom::Path {
+    let half = thickness / 2.0;
+
+    dom::Path {
+        fill: style.fill.clone(),
+        stroke: style.stroke.clone(),
+        segments: vec![
+            dom::PathSegment::MoveTo { x: x0, y: y - half },
+            dom::PathSegment::LineTo { x: x1, y: y - half },
+            dom::PathSegment::LineTo { x: x1, y: y + half },
+            dom::PathSegment::LineTo { x: x0, y: y + half },
+            dom::PathSegment::ClosePath,
+        ],
     }
 }